@@ -1,20 +1,26 @@
 use std::path::Path;
 use std::fs::File;
-use std::io::{Read, stdin};
+use std::io::{self, Cursor, ErrorKind, Read, Write, stdin};
 use std::env::args;
 
+use flate2::read::MultiGzDecoder;
+
 // test cases
 //   .read from file: cq -in <file>
 //   .read from stdin by default: cq
 //   .read a column: cq -select city
 //   .read columns: cq -select city county
 //   read rows: cq -where state -eq WA
+//   read rows (comparisons): cq -where pop -gt 100000 -numeric
 //   read column(s) from a row: cq -c city county -where state -eq WA
 //   change delimiter: cq -delim "|"
+//   change quote char: cq -quote "'"
+//   select columns by index range: cq -fields 1-3,5,7-
+//   treat the first row as data: cq -no-header -fields 2
+//   filter by column index: cq -where #3 -eq WA
+//   read gzip-compressed input transparently: cq -in data.csv.gz
+//   fuzzy-match and rank rows best-match-first: cq -where city -fuzzy seatl
 //   tbd: inserts, updates, deletes
-//   tbd: quotes, line separator, escaping
-//   tbd: header vs index
-//   tbd: encoding other than ut8
 
 struct Iterator<T> {
     items: Vec<T>,
@@ -47,132 +53,478 @@ impl <T> Iterator<T> {
     }
 }
 
+#[derive(Clone, Copy)]
+enum Operator {
+    Eq,
+    Ne,
+    Lt,
+    Le,
+    Gt,
+    Ge,
+}
+
+#[derive(Clone, Copy)]
+enum CompareMode {
+    Lexical,
+    Numeric,
+}
+
+enum ColumnRef {
+    Name(String),
+    Index(usize), // 1-based
+}
+
 struct Filter {
-    column: String,
+    column: ColumnRef,
+    operator: Operator,
     value: String,
+    compare_mode: CompareMode,
+}
+
+// A cut-style 1-based, inclusive column range, e.g. "1-3", "5", "-3" (from start) or "7-" (to end).
+struct Range {
+    low: Option<usize>,
+    high: Option<usize>,
+}
+
+fn parse_range(spec: &str) -> Result<Range, String> {
+    let err = || format!("Invalid range '{}'", spec);
+
+    match spec.find('-') {
+        Some(dash) => {
+            let (low, high) = spec.split_at(dash);
+            let high = &high[1..];
+            Ok(Range {
+                low: if low.is_empty() { None } else { Some(low.parse().map_err(|_| err())?) },
+                high: if high.is_empty() { None } else { Some(high.parse().map_err(|_| err())?) },
+            })
+        }
+        None => {
+            let n = spec.parse().map_err(|_| err())?;
+            Ok(Range { low: Some(n), high: Some(n) })
+        }
+    }
+}
+
+fn parse_ranges(spec: &str) -> Result<Vec<Range>, String> {
+    spec.split(',').map(parse_range).collect()
+}
+
+fn ranges_contain(ranges: &[Range], column_index: usize) -> bool {
+    let one_based = column_index + 1;
+    ranges.iter().any(|r| {
+        r.low.is_none_or(|low| one_based >= low) && r.high.is_none_or(|high| one_based <= high)
+    })
 }
 
 struct FilterState {
     column_index: usize,
+    operator: Operator,
     value: String,
+    compare_mode: CompareMode,
+    matched: bool,
+}
+
+fn compare_matches(operator: Operator, compare_mode: CompareMode, value: &str, filter_value: &str) -> bool {
+    let ordering = match compare_mode {
+        CompareMode::Numeric => {
+            match (value.parse::<f64>(), filter_value.parse::<f64>()) {
+                (Ok(a), Ok(b)) => a.partial_cmp(&b).unwrap_or(std::cmp::Ordering::Equal),
+                _ => value.cmp(filter_value),
+            }
+        }
+        CompareMode::Lexical => value.cmp(filter_value),
+    };
+
+    match operator {
+        Operator::Eq => ordering == std::cmp::Ordering::Equal,
+        Operator::Ne => ordering != std::cmp::Ordering::Equal,
+        Operator::Lt => ordering == std::cmp::Ordering::Less,
+        Operator::Le => ordering != std::cmp::Ordering::Greater,
+        Operator::Gt => ordering == std::cmp::Ordering::Greater,
+        Operator::Ge => ordering != std::cmp::Ordering::Less,
+    }
+}
+
+struct FuzzyFilter {
+    column: ColumnRef,
+    query: String,
+}
+
+struct FuzzyFilterState {
+    column_index: usize,
+    query: String,
     matched: bool,
+    score: f64,
+}
+
+// Subsequence fuzzy-matching constants, skim/fzy-style: a flat score per matched
+// character, a bonus for runs of consecutive matches, a bonus for landing on a
+// word boundary, and a small penalty for each field character skipped before, between
+// or after the matches.
+const FUZZY_SCORE_MATCH: f64 = 10.0;
+const FUZZY_BONUS_CONSECUTIVE: f64 = 5.0;
+const FUZZY_BONUS_BOUNDARY: f64 = 4.0;
+const FUZZY_PENALTY_GAP: f64 = 1.0;
+
+fn fuzzy_boundary_bonus(field: &[char], index: usize) -> f64 {
+    if index == 0 {
+        return FUZZY_BONUS_BOUNDARY;
+    }
+
+    let prev = field[index - 1];
+    let current = field[index];
+    let is_boundary = prev == ' ' || prev == '_' || prev == '-'
+        || (prev.is_lowercase() && current.is_uppercase());
+
+    if is_boundary {
+        FUZZY_BONUS_BOUNDARY
+    } else {
+        0.0
+    }
+}
+
+// Scores `field` against `query` as a subsequence match, case-insensitively, returning
+// `None` if `query`'s characters don't all appear in `field` in order.
+fn fuzzy_score(query: &str, field: &str) -> Option<f64> {
+    let field_chars: Vec<char> = field.chars().collect();
+    let query_lower: Vec<char> = query.chars().flat_map(|c| c.to_lowercase()).collect();
+    let field_lower: Vec<char> = field.chars().flat_map(|c| c.to_lowercase()).collect();
+
+    let query_len = query_lower.len();
+    let field_len = field_lower.len();
+    if query_len == 0 {
+        return Some(0.0);
+    }
+    if field_len < query_len {
+        return None;
+    }
+
+    const NEG_INF: f64 = f64::NEG_INFINITY;
+    // matched[i][j]: best score ending with query[i] matched to field[j].
+    // best[i][j]: best score matching query[0..=i] somewhere within field[0..=j].
+    let mut matched = vec![vec![NEG_INF; field_len]; query_len];
+    let mut best = vec![vec![NEG_INF; field_len]; query_len];
+
+    for i in 0 .. query_len {
+        for j in 0 .. field_len {
+            if query_lower[i] == field_lower[j] {
+                let bonus = fuzzy_boundary_bonus(&field_chars, j);
+                matched[i][j] = if i == 0 {
+                    FUZZY_SCORE_MATCH + bonus - FUZZY_PENALTY_GAP * (j as f64)
+                } else if j == 0 {
+                    NEG_INF
+                } else {
+                    let consecutive = matched[i - 1][j - 1] + FUZZY_SCORE_MATCH + FUZZY_BONUS_CONSECUTIVE + bonus;
+                    let fresh = best[i - 1][j - 1] + FUZZY_SCORE_MATCH + bonus;
+                    consecutive.max(fresh)
+                };
+            }
+
+            let skip = if j > 0 { best[i][j - 1] - FUZZY_PENALTY_GAP } else { NEG_INF };
+            best[i][j] = matched[i][j].max(skip);
+        }
+    }
+
+    let score = best[query_len - 1][field_len - 1];
+    if score.is_finite() {
+        Some(score)
+    } else {
+        None
+    }
 }
 
 struct ReaderArgs {
     input: Box<dyn Read>,
     columns: Vec<String>,
     filters: Vec<Filter>,
+    fuzzy: Vec<FuzzyFilter>,
+    delim: u8,
+    quote: u8,
+    fields: Vec<Range>,
+    no_header: bool,
 }
 
 struct ReaderState {
     column_indexes: Vec<usize>,
     filters: Vec<FilterState>,
+    fuzzy: Vec<FuzzyFilterState>,
     in_header: bool,
     column_index: usize,
     current_value: Vec<u8>,
     buf: Vec<u8>,
     to_print: Vec<String>,
+    in_quotes: bool,
+    quote_pending: bool,
+    buffered: Vec<(f64, String)>,
+    after_cr: bool,
+}
+
+// Peeks at the first two bytes of `input` and, if they're the gzip magic number,
+// transparently wraps it in a streaming decoder; otherwise returns the bytes read
+// chained back in front of the rest of the stream, unchanged.
+fn detect_gzip(mut input: Box<dyn Read>) -> Box<dyn Read> {
+    let mut magic = [0u8; 2];
+    let mut filled = 0;
+    while filled < magic.len() {
+        match input.read(&mut magic[filled..]) {
+            Ok(0) => break,
+            Ok(n) => filled += n,
+            Err(_) => break,
+        }
+    }
+
+    let prefix: Box<dyn Read> = Box::new(Cursor::new(magic[..filled].to_vec()).chain(input));
+    if filled == 2 && magic == [0x1f, 0x8b] {
+        Box::new(MultiGzDecoder::new(prefix))
+    } else {
+        prefix
+    }
+}
+
+fn needs_quoting(value: &str, args: &ReaderArgs) -> bool {
+    value.bytes().any(|b| b == args.delim || b == args.quote || b == 13 || b == 10)
+}
+
+fn format_field(value: &str, args: &ReaderArgs) -> String {
+    if needs_quoting(value, args) {
+        let quote = args.quote as char;
+        let escaped = value.replace(quote, &format!("{}{}", quote, quote));
+        format!("{}{}{}", quote, escaped, quote)
+    } else {
+        value.to_string()
+    }
+}
+
+fn format_line(fields: &[String], args: &ReaderArgs) -> String {
+    let delim = args.delim as char;
+    fields.iter()
+        .map(|value| format_field(value, args))
+        .collect::<Vec<_>>()
+        .join(&delim.to_string())
+}
+
+fn is_broken_pipe(err: &io::Error) -> bool {
+    err.kind() == ErrorKind::BrokenPipe
+}
+
+// Writes a line to `out`, treating a closed pipe (e.g. `cq ... | head`) as a clean
+// exit rather than an error.
+fn write_line(out: &mut dyn Write, line: &str) -> Result<(), String> {
+    match writeln!(out, "{}", line) {
+        Ok(()) => Ok(()),
+        Err(e) if is_broken_pipe(&e) => std::process::exit(0),
+        Err(e) => Err(format!("Failed to write output: {}", e)),
+    }
 }
 
 fn main() -> Result<(), String> {
     let cmd_args: Vec<String> = args().collect();
-    let mut args = parse_args(Iterator::new(cmd_args))?;
+    let args = parse_args(Iterator::new(cmd_args))?;
+    let stdout = io::stdout();
+    let mut out = stdout.lock();
+    run(args, &mut out)
+}
+
+fn run(mut args: ReaderArgs, out: &mut dyn Write) -> Result<(), String> {
     let mut state = ReaderState {
         column_indexes: vec!(),
         filters: vec!(),
-        in_header: true,
+        fuzzy: vec!(),
+        in_header: !args.no_header,
         column_index: 0,
         current_value: vec!(),
         buf: vec!(0, 0, 0),
         to_print: vec!(),
+        in_quotes: false,
+        quote_pending: false,
+        buffered: vec!(),
+        after_cr: false,
     };
 
-    let mut len = args.input.read(&mut state.buf).unwrap();
+    for filter in args.filters.iter() {
+        if let ColumnRef::Index(n) = filter.column {
+            state.filters.push(FilterState {
+                column_index: n - 1,
+                operator: filter.operator,
+                value: filter.value.to_string(),
+                compare_mode: filter.compare_mode,
+                matched: false,
+            });
+        }
+    }
+
+    for filter in args.fuzzy.iter() {
+        if let ColumnRef::Index(n) = filter.column {
+            state.fuzzy.push(FuzzyFilterState {
+                column_index: n - 1,
+                query: filter.query.to_string(),
+                matched: false,
+                score: 0.0,
+            });
+        }
+    }
+
+    let mut len = args.input.read(&mut state.buf).map_err(|e| format!("Failed to read input: {}", e))?;
     while len > 0 {
         for i in 0 .. len {
-            match state.buf[i] {
-                10 => {
-                    // Truly ignore
+            let byte = state.buf[i];
+
+            if state.quote_pending {
+                state.quote_pending = false;
+                if byte == args.quote {
+                    state.current_value.push(byte);
+                    state.in_quotes = true;
+                    continue;
+                }
+            }
+
+            if state.in_quotes {
+                if byte == args.quote {
+                    // Closing quote or the first half of a doubled "" escape - decided
+                    // once we see the next byte.
+                    state.in_quotes = false;
+                    state.quote_pending = true;
+                } else {
+                    state.current_value.push(byte);
                 }
-                13 => {
-                    handle_value_end(&args, &mut state);
-                    handle_line_end(&args, &mut state);
+                continue;
+            }
+
+            if byte == args.quote && state.current_value.is_empty() {
+                state.after_cr = false;
+                state.in_quotes = true;
+                continue;
+            }
+
+            if byte == 10 {
+                // A bare LF ends a row, unless it's the second half of a CRLF pair
+                // we already handled when we saw the CR.
+                if state.after_cr {
+                    state.after_cr = false;
+                } else {
+                    handle_value_end(&args, &mut state)?;
+                    handle_line_end(&args, &mut state, out)?;
                 }
-                44 => {
-                    handle_value_end(&args, &mut state);
-                },
-                _ => {
-                    state.current_value.push(state.buf[i])
+            } else if byte == 13 {
+                state.after_cr = true;
+                handle_value_end(&args, &mut state)?;
+                handle_line_end(&args, &mut state, out)?;
+            } else {
+                state.after_cr = false;
+                if byte == args.delim {
+                    handle_value_end(&args, &mut state)?;
+                } else {
+                    state.current_value.push(byte)
                 }
             }
         }
 
-        len = args.input.read(&mut state.buf).unwrap();
+        len = args.input.read(&mut state.buf).map_err(|e| format!("Failed to read input: {}", e))?;
+    }
+    handle_value_end(&args, &mut state)?;
+    handle_line_end(&args, &mut state, out)?;
+
+    if !args.fuzzy.is_empty() {
+        state.buffered.sort_by(|a, b| b.0.partial_cmp(&a.0).unwrap_or(std::cmp::Ordering::Equal));
+        for (_, line) in state.buffered.iter() {
+            write_line(out, line)?;
+        }
     }
-    handle_value_end(&args, &mut state);
-    handle_line_end(&args, &mut state);
 
     Ok(())
 }
 
-fn handle_line_end(_args: &ReaderArgs, state: &mut ReaderState) {
+fn handle_line_end(args: &ReaderArgs, state: &mut ReaderState, out: &mut dyn Write) -> Result<(), String> {
     state.in_header = false;
     state.column_index = 0;
     state.current_value = vec!();
 
-    if state.filters.iter().all(|f| f.matched) {
-        if state.to_print.len() > 0 {
-            let mut first = true;
-            for value in state.to_print.iter() {
-                if !first {
-                    print!(",");
-                }
-                first = false;
-                print!("{}", value);
-            }
-
-            state.to_print.clear();
-            println!();
+    if state.filters.iter().all(|f| f.matched) && state.fuzzy.iter().all(|f| f.matched) && !state.to_print.is_empty() {
+        let line = format_line(&state.to_print, args);
+        if args.fuzzy.is_empty() {
+            write_line(out, &line)?;
+        } else {
+            let score = state.fuzzy.iter().map(|f| f.score).sum();
+            state.buffered.push((score, line));
         }
     }
 
+    state.to_print.clear();
+
     for filter in state.filters.iter_mut() {
         filter.matched = false;
     }
+
+    for filter in state.fuzzy.iter_mut() {
+        filter.matched = false;
+        filter.score = 0.0;
+    }
+
+    Ok(())
 }
 
-fn handle_value_end(args: &ReaderArgs, state: &mut ReaderState) {
+fn handle_value_end(args: &ReaderArgs, state: &mut ReaderState) -> Result<(), String> {
     if state.in_header {
-        let value = std::str::from_utf8(state.current_value.as_slice()).unwrap();
-        if args.columns.contains(&value.to_string()) {
+        let value = String::from_utf8_lossy(state.current_value.as_slice()).into_owned();
+        if args.columns.contains(&value) {
             state.column_indexes.push(state.column_index);
         }
-        if let Some(filter) = args.filters.iter().find(|f| &f.column == value) {
+        let named_filter = args.filters.iter().find(|f| matches!(&f.column, ColumnRef::Name(name) if name == &value));
+        if let Some(filter) = named_filter {
             state.filters.push(FilterState {
                 column_index: state.column_index,
+                operator: filter.operator,
                 value: filter.value.to_string(),
+                compare_mode: filter.compare_mode,
+                matched: false,
+            });
+        }
+        let named_fuzzy = args.fuzzy.iter().find(|f| matches!(&f.column, ColumnRef::Name(name) if name == &value));
+        if let Some(filter) = named_fuzzy {
+            state.fuzzy.push(FuzzyFilterState {
+                column_index: state.column_index,
+                query: filter.query.to_string(),
                 matched: false,
+                score: 0.0,
             });
         }
     } else {
-        if state.column_indexes.contains(&state.column_index) || args.columns.len() == 0 {
-            let value = String::from_utf8(state.current_value.clone()).unwrap();
+        let no_selection = args.columns.is_empty() && args.fields.is_empty();
+        if no_selection
+            || state.column_indexes.contains(&state.column_index)
+            || ranges_contain(&args.fields, state.column_index) {
+            let value = String::from_utf8_lossy(state.current_value.as_slice()).into_owned();
             state.to_print.push(value);
         }
 
-        let column_index = state.column_index.clone();
+        let column_index = state.column_index;
         let filter_maybe =
             state.filters.iter_mut().find(|f| f.column_index == column_index);
         if let Some(filter) = filter_maybe {
-            let value = String::from_utf8(state.current_value.clone()).unwrap();
-            if value == filter.value {
+            let value = String::from_utf8_lossy(state.current_value.as_slice()).into_owned();
+            if compare_matches(filter.operator, filter.compare_mode, &value, &filter.value) {
+                filter.matched = true;
+            }
+        }
+
+        let fuzzy_maybe =
+            state.fuzzy.iter_mut().find(|f| f.column_index == column_index);
+        if let Some(filter) = fuzzy_maybe {
+            let value = String::from_utf8_lossy(state.current_value.as_slice()).into_owned();
+            if let Some(score) = fuzzy_score(&filter.query, &value) {
                 filter.matched = true;
+                filter.score = score;
             }
         }
     }
 
     state.column_index += 1;
     state.current_value = vec!();
+    state.in_quotes = false;
+    state.quote_pending = false;
+
+    Ok(())
 }
 
 fn parse_args(mut args: Iterator<String>) -> Result<ReaderArgs, String> {
@@ -182,6 +534,11 @@ fn parse_args(mut args: Iterator<String>) -> Result<ReaderArgs, String> {
         input: Box::new(stdin()),
         columns: vec!(),
         filters: vec!(),
+        fuzzy: vec!(),
+        delim: b',',
+        quote: b'"',
+        fields: vec!(),
+        no_header: false,
     };
 
     while let Some(arg) = args.accept() {
@@ -189,8 +546,18 @@ fn parse_args(mut args: Iterator<String>) -> Result<ReaderArgs, String> {
             "-in" => {
                 let filename = args.expect("Expected filename after -in".to_string())?;
                 let path = Path::new(filename);
-                result.input = Box::new(File::open(&path).unwrap());
+                result.input = Box::new(File::open(path).map_err(|e| format!("Failed to open '{}': {}", filename, e))?);
             },
+            "-delim" => {
+                let delim = args.expect("Expected a delimiter character after -delim".to_string())?;
+                result.delim = *delim.as_bytes().first()
+                    .ok_or("Expected a non-empty delimiter after -delim".to_string())?;
+            }
+            "-quote" => {
+                let quote = args.expect("Expected a quote character after -quote".to_string())?;
+                result.quote = *quote.as_bytes().first()
+                    .ok_or("Expected a non-empty quote character after -quote".to_string())?;
+            }
             "-select" => {
                 while let Some(select) = args.accept() {
                     if select.starts_with("-") {
@@ -201,21 +568,265 @@ fn parse_args(mut args: Iterator<String>) -> Result<ReaderArgs, String> {
                     }
                 }
             }
+            "-fields" => {
+                let spec = args.expect("Expected a range spec after -fields".to_string())?;
+                result.fields = parse_ranges(spec)?;
+            }
+            "-no-header" => {
+                result.no_header = true;
+            }
             "-where" => {
-                let column = args.expect("Expected column name after -where".to_string())?.to_string();
-                let eq = args.expect("Expected -eq as part of -where".to_string())?;
-                if eq != "-eq" {
-                    return Err(format!("Expected -eq, but got '{}'", eq));
+                let column_arg = args.expect("Expected column name after -where".to_string())?.to_string();
+                let column = match column_arg.strip_prefix('#') {
+                    Some(index) => {
+                        let n: usize = index.parse().map_err(|_| format!("Invalid column index '{}'", column_arg))?;
+                        if n == 0 {
+                            return Err("Column index must be 1 or greater".to_string());
+                        }
+                        ColumnRef::Index(n)
+                    }
+                    None => ColumnRef::Name(column_arg),
+                };
+                let op = args.expect("Expected a comparison operator (-eq, -ne, -lt, -le, -gt, -ge, -fuzzy) as part of -where".to_string())?;
+                if op == "-fuzzy" {
+                    let query = args.expect("Expected a query after -fuzzy".to_string())?.to_string();
+                    result.fuzzy.push(FuzzyFilter {
+                        column,
+                        query,
+                    });
+                    continue;
+                }
+
+                let operator = match op.as_str() {
+                    "-eq" => Operator::Eq,
+                    "-ne" => Operator::Ne,
+                    "-lt" => Operator::Lt,
+                    "-le" => Operator::Le,
+                    "-gt" => Operator::Gt,
+                    "-ge" => Operator::Ge,
+                    _ => return Err(format!("Expected a comparison operator (-eq, -ne, -lt, -le, -gt, -ge, -fuzzy), but got '{}'", op)),
+                };
+                let value = args.expect("Expected value after comparison operator".to_string())?.to_string();
+
+                let mut compare_mode = CompareMode::Lexical;
+                if let Some(hint) = args.accept() {
+                    if hint == "-numeric" || hint == "-num" {
+                        compare_mode = CompareMode::Numeric;
+                    } else {
+                        args.refund();
+                    }
                 }
-                let value = args.expect("Expected value after -eq".to_string())?.to_string();
+
                 result.filters.push(Filter {
                     column,
+                    operator,
                     value,
+                    compare_mode,
                 })
             }
             _ => return Err(format!("Unknown argument '{}'", arg)),
         }
     }
 
+    if result.no_header {
+        let name_err = "Named column references are not supported with -no-header; use #N instead".to_string();
+        if !result.columns.is_empty() {
+            return Err("-select is not supported with -no-header; use -fields instead".to_string());
+        }
+        let has_named_ref = result.filters.iter().any(|f| matches!(f.column, ColumnRef::Name(_)))
+            || result.fuzzy.iter().any(|f| matches!(f.column, ColumnRef::Name(_)));
+        if has_named_ref {
+            return Err(name_err);
+        }
+    }
+
+    result.input = detect_gzip(result.input);
+
     Ok(result)
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use flate2::write::GzEncoder;
+    use flate2::Compression;
+
+    fn test_args(csv: &str) -> ReaderArgs {
+        test_args_bytes(csv.as_bytes().to_vec())
+    }
+
+    fn test_args_bytes(input: Vec<u8>) -> ReaderArgs {
+        ReaderArgs {
+            input: Box::new(Cursor::new(input)),
+            columns: vec!(),
+            filters: vec!(),
+            fuzzy: vec!(),
+            delim: b',',
+            quote: b'"',
+            fields: vec!(),
+            no_header: false,
+        }
+    }
+
+    fn run_to_string(args: ReaderArgs) -> String {
+        let mut out = Vec::new();
+        run(args, &mut out).unwrap();
+        String::from_utf8(out).unwrap()
+    }
+
+    fn where_filter(column: &str, operator: Operator, value: &str, compare_mode: CompareMode) -> Filter {
+        Filter {
+            column: ColumnRef::Name(column.to_string()),
+            operator,
+            value: value.to_string(),
+            compare_mode,
+        }
+    }
+
+    #[test]
+    fn where_lexical_lt_compares_as_strings() {
+        let mut args = test_args("name,pop\r\na,9\r\nb,10");
+        args.filters = vec!(where_filter("pop", Operator::Lt, "9", CompareMode::Lexical));
+        // Lexically "10" < "9", so the numerically-smaller row is excluded.
+        assert_eq!(run_to_string(args), "b,10\n");
+    }
+
+    #[test]
+    fn where_numeric_lt_compares_as_numbers() {
+        let mut args = test_args("name,pop\r\na,9\r\nb,10");
+        args.filters = vec!(where_filter("pop", Operator::Lt, "10", CompareMode::Numeric));
+        assert_eq!(run_to_string(args), "a,9\n");
+    }
+
+    #[test]
+    fn where_numeric_falls_back_to_lexical_when_unparseable() {
+        let mut args = test_args("name,pop\r\na,nine\r\nb,ten");
+        args.filters = vec!(where_filter("pop", Operator::Eq, "nine", CompareMode::Numeric));
+        assert_eq!(run_to_string(args), "a,nine\n");
+    }
+
+    #[test]
+    fn parse_range_single_number() {
+        let range = parse_range("5").unwrap();
+        assert_eq!(range.low, Some(5));
+        assert_eq!(range.high, Some(5));
+    }
+
+    #[test]
+    fn parse_range_inclusive_bounds() {
+        let range = parse_range("2-4").unwrap();
+        assert_eq!(range.low, Some(2));
+        assert_eq!(range.high, Some(4));
+    }
+
+    #[test]
+    fn parse_range_from_start() {
+        let range = parse_range("-3").unwrap();
+        assert_eq!(range.low, None);
+        assert_eq!(range.high, Some(3));
+    }
+
+    #[test]
+    fn parse_range_to_end() {
+        let range = parse_range("7-").unwrap();
+        assert_eq!(range.low, Some(7));
+        assert_eq!(range.high, None);
+    }
+
+    #[test]
+    fn parse_range_rejects_garbage() {
+        assert!(parse_range("a-b").is_err());
+    }
+
+    #[test]
+    fn parse_ranges_splits_on_comma() {
+        let ranges = parse_ranges("1-3,5,7-").unwrap();
+        assert_eq!(ranges.len(), 3);
+        assert!(ranges_contain(&ranges, 0)); // column 1
+        assert!(ranges_contain(&ranges, 4)); // column 5
+        assert!(ranges_contain(&ranges, 20)); // column 21, covered by "7-"
+        assert!(!ranges_contain(&ranges, 3)); // column 4, in the gap
+    }
+
+    #[test]
+    fn fuzzy_score_requires_subsequence_in_order() {
+        assert!(fuzzy_score("seat", "Seattle").is_some());
+        assert!(fuzzy_score("seat", "Spokane").is_none());
+        assert!(fuzzy_score("seat", "Satellite").is_none()); // no 'a' follows an 'e' here
+    }
+
+    #[test]
+    fn fuzzy_score_ranks_tighter_matches_higher() {
+        let tight = fuzzy_score("seat", "Seattle").unwrap();
+        let loose = fuzzy_score("seat", "Southeastern Annotated Town").unwrap();
+        assert!(tight > loose);
+    }
+
+    #[test]
+    fn fuzzy_score_empty_query_matches_anything() {
+        assert_eq!(fuzzy_score("", "anything"), Some(0.0));
+    }
+
+    #[test]
+    fn csv_parses_quoted_field_with_embedded_delimiter() {
+        let args = test_args("city,state\r\n\"Seattle, WA\",WA");
+        assert_eq!(run_to_string(args), "\"Seattle, WA\",WA\n");
+    }
+
+    #[test]
+    fn csv_unescapes_doubled_quotes() {
+        let args = test_args("a,b\r\n1,\"has \"\"q\"\" here\"");
+        assert_eq!(run_to_string(args), "1,\"has \"\"q\"\" here\"\n");
+    }
+
+    #[test]
+    fn csv_respects_configured_delimiter() {
+        let mut args = test_args("a;b\r\n1;2");
+        args.delim = b';';
+        assert_eq!(run_to_string(args), "1;2\n");
+    }
+
+    #[test]
+    fn csv_respects_configured_quote_char() {
+        let mut args = test_args("city,state\r\n'Seattle, WA',WA");
+        args.quote = b'\'';
+        assert_eq!(run_to_string(args), "'Seattle, WA',WA\n");
+    }
+
+    #[test]
+    fn csv_handles_bare_lf_line_endings() {
+        let args = test_args("a,b\n1,2\n3,4");
+        assert_eq!(run_to_string(args), "1,2\n3,4\n");
+    }
+
+    #[test]
+    fn csv_handles_mixed_crlf_and_lf_line_endings() {
+        let args = test_args("a,b\r\n1,2\n3,4\r\n5,6");
+        assert_eq!(run_to_string(args), "1,2\n3,4\n5,6\n");
+    }
+
+    #[test]
+    fn run_decodes_gzip_compressed_input() {
+        let mut encoder = GzEncoder::new(Vec::new(), Compression::default());
+        encoder.write_all(b"city,state\r\nSeattle,WA").unwrap();
+        let gz_bytes = encoder.finish().unwrap();
+
+        let mut args = test_args("");
+        args.input = detect_gzip(Box::new(Cursor::new(gz_bytes)));
+        assert_eq!(run_to_string(args), "Seattle,WA\n");
+    }
+
+    #[test]
+    fn run_replaces_invalid_utf8_instead_of_failing() {
+        let mut input = b"a,b\r\n1,".to_vec();
+        input.push(0xff); // not valid UTF-8 on its own
+        let args = test_args_bytes(input);
+        assert_eq!(run_to_string(args), "1,\u{fffd}\n");
+    }
+
+    #[test]
+    fn broken_pipe_is_treated_as_a_clean_exit_condition() {
+        assert!(is_broken_pipe(&io::Error::from(ErrorKind::BrokenPipe)));
+        assert!(!is_broken_pipe(&io::Error::from(ErrorKind::PermissionDenied)));
+    }
+}